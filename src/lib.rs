@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::io::Cursor;
+use std::str::FromStr;
 use heed::EnvOpenOptions;
 use milli::documents::{DocumentsBatchBuilder, DocumentsBatchReader};
 use milli::{Criterion, DefaultSearchLogger, execute_search, filtered_universe, GeoSortStrategy, Object, SearchContext, TermsMatchingStrategy, TimeBudget};
@@ -8,6 +9,35 @@ use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
 
 
+/// Parse a single CSV cell given its column header, honoring milli's
+/// `field:type` header syntax. A `:number` suffix yields a JSON number and a
+/// `:boolean` suffix a JSON boolean; any other (or absent) type is kept as a
+/// string. Returns the bare field name together with the typed value.
+fn parse_csv_cell(header: &str, cell: &str) -> Result<(String, serde_json::Value), String> {
+    let (name, ty) = match header.rsplit_once(':') {
+        Some((name, ty)) => (name, Some(ty)),
+        None => (header, None),
+    };
+    let value = match ty {
+        Some("number") => {
+            let number = cell
+                .parse::<i64>()
+                .map(serde_json::Number::from)
+                .or_else(|_| cell.parse::<u64>().map(serde_json::Number::from))
+                .ok()
+                .or_else(|| cell.parse::<f64>().ok().and_then(serde_json::Number::from_f64))
+                .ok_or_else(|| format!("`{cell}` is not a valid number"))?;
+            serde_json::Value::Number(number)
+        }
+        Some("boolean") => serde_json::Value::Bool(
+            cell.parse::<bool>()
+                .map_err(|_| format!("`{cell}` is not a valid boolean"))?,
+        ),
+        _ => serde_json::Value::String(cell.to_owned()),
+    };
+    Ok((name.to_owned(), value))
+}
+
 #[pyclass]
 struct MilliEmbedded {
     index: milli::Index,
@@ -16,11 +46,13 @@ struct MilliEmbedded {
 #[pymethods]
 impl MilliEmbedded {
     #[new]
-    fn new(index_path: &str, searchable_fields: Vec<String>, filterable_fields: HashSet<String>) -> PyResult<Self> {
+    #[pyo3(signature = (index_path, searchable_fields, filterable_fields, map_size_bytes, primary_key=None, criteria=None, min_word_len_one_typo=None, min_word_len_two_typos=None))]
+    fn new(index_path: &str, searchable_fields: Vec<String>, filterable_fields: HashSet<String>, map_size_bytes: usize, primary_key: Option<String>, criteria: Option<Vec<String>>, min_word_len_one_typo: Option<u8>, min_word_len_two_typos: Option<u8>) -> PyResult<Self> {
         std::fs::create_dir_all(&index_path)
             .map_err(|e| PyRuntimeError::new_err(format!("Cannot create index path: {e}")))?;
 
-        let options = EnvOpenOptions::new();
+        let mut options = EnvOpenOptions::new();
+        options.map_size(map_size_bytes);
         let index = milli::Index::new(options, index_path)
             .map_err(|e| PyRuntimeError::new_err(format!("Cannot create index, {e}")))?;
 
@@ -29,14 +61,31 @@ impl MilliEmbedded {
         let mut builder = Settings::new(&mut wtxn, &index, &config);
         builder.set_searchable_fields(searchable_fields);
         builder.set_filterable_fields(filterable_fields);
-        builder.set_criteria(vec![
-            Criterion::Words,
-            Criterion::Typo,
-            Criterion::Proximity,
-            Criterion::Attribute,
-            Criterion::Sort,
-            Criterion::Exactness,
-        ]);
+        if let Some(primary_key) = primary_key {
+            builder.set_primary_key(primary_key);
+        }
+        let criteria = match criteria {
+            Some(criteria) => criteria
+                .iter()
+                .map(|criterion| Criterion::from_str(criterion))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| PyRuntimeError::new_err(format!("Cannot parse criterion, {e}")))?,
+            None => vec![
+                Criterion::Words,
+                Criterion::Typo,
+                Criterion::Proximity,
+                Criterion::Attribute,
+                Criterion::Sort,
+                Criterion::Exactness,
+            ],
+        };
+        builder.set_criteria(criteria);
+        if let Some(min_word_len_one_typo) = min_word_len_one_typo {
+            builder.set_min_word_len_one_typo(min_word_len_one_typo);
+        }
+        if let Some(min_word_len_two_typos) = min_word_len_two_typos {
+            builder.set_min_word_len_two_typos(min_word_len_two_typos);
+        }
 
         builder.execute(|_| (), || false).map_err(|e| PyRuntimeError::new_err(format!("Cannot execute index builder, {e}")))?;
 
@@ -45,7 +94,8 @@ impl MilliEmbedded {
         Ok(Self { index })
     }
 
-    fn mutate(&self, py: Python, add_jsonl: String, remove_ids: Vec<String>) -> PyResult<(u64, u64)> {
+    #[pyo3(signature = (add_documents, remove_ids, format="jsonl".to_string()))]
+    fn mutate(&self, py: Python, add_documents: String, remove_ids: Vec<String>, format: String) -> PyResult<(u64, u64)> {
         let mut build_res = DocumentAdditionResult {
             indexed_documents: 0,
             number_of_documents: 0,
@@ -67,17 +117,52 @@ impl MilliEmbedded {
                 build_res = builder.execute().map_err(|e| PyRuntimeError::new_err(format!("Cannot execute builder, {e}")))?;
             }
 
-            if !add_jsonl.is_empty() {
-                let indexing_config = IndexDocumentsConfig::default();
+            if !add_documents.is_empty() {
+                let autogenerate_docids = self.index
+                    .primary_key(&wtxn)
+                    .map_err(|e| PyRuntimeError::new_err(format!("Cannot read primary key, {e}")))?
+                    .is_none();
+                let indexing_config = IndexDocumentsConfig { autogenerate_docids, ..Default::default() };
                 let builder =
                     IndexDocuments::new(&mut wtxn, &self.index, &config, indexing_config, |_| (), || false)
                         .map_err(|e| PyRuntimeError::new_err(format!("Cannot create index documents builder, {e}")))?;
 
                 let mut sources = DocumentsBatchBuilder::new(Vec::new());
 
-                for result in serde_json::Deserializer::from_str(&add_jsonl).into_iter::<Object>() {
-                    let object = result.map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
-                    sources.append_json_object(&object).map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
+                match format.as_str() {
+                    "jsonl" => {
+                        for result in serde_json::Deserializer::from_str(&add_documents).into_iter::<Object>() {
+                            let object = result.map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
+                            sources.append_json_object(&object).map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
+                        }
+                    }
+                    "json" => {
+                        let objects: Vec<Object> = serde_json::from_str(&add_documents)
+                            .map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize json array, {e}")))?;
+                        for object in &objects {
+                            sources.append_json_object(object).map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
+                        }
+                    }
+                    "csv" => {
+                        let mut reader = csv::Reader::from_reader(Cursor::new(add_documents.as_bytes()));
+                        let headers = reader
+                            .headers()
+                            .map_err(|e| PyRuntimeError::new_err(format!("Cannot read csv headers, {e}")))?
+                            .clone();
+                        for result in reader.records() {
+                            let record = result.map_err(|e| PyRuntimeError::new_err(format!("Cannot read csv record, {e}")))?;
+                            let mut object = Object::new();
+                            for (header, cell) in headers.iter().zip(record.iter()) {
+                                let (name, value) = parse_csv_cell(header, cell)
+                                    .map_err(|e| PyRuntimeError::new_err(format!("Cannot parse csv cell, {e}")))?;
+                                object.insert(name, value);
+                            }
+                            sources.append_json_object(&object).map_err(|e| PyRuntimeError::new_err(format!("Cannot deserialize object, {e}")))?;
+                        }
+                    }
+                    other => {
+                        return Err(PyRuntimeError::new_err(format!("Unknown document format, {other}")));
+                    }
                 }
 
                 let sources = sources.into_inner().map_err(|e| PyRuntimeError::new_err(format!("Cannot get sources, {e}")))?;
@@ -97,22 +182,41 @@ impl MilliEmbedded {
         Ok((build_res.indexed_documents, build_res.number_of_documents))
     }
 
-    fn search(&self, py: Python, query: String, return_fields: HashSet<String>) -> PyResult<String> {
+    #[pyo3(signature = (query, return_fields, filter=None, offset=0, limit=20, sort=None, with_score=false))]
+    fn search(&self, py: Python, query: String, return_fields: HashSet<String>, filter: Option<String>, offset: usize, limit: usize, sort: Option<Vec<String>>, with_score: bool) -> PyResult<String> {
         py.allow_threads(|| {
             let txn = self.index.read_txn().map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
             let mut ctx = SearchContext::new(&self.index, &txn).map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
-            let universe = filtered_universe(&ctx, &None).map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
+            let filter = match &filter {
+                Some(expr) => milli::Filter::from_str(expr).map_err(|e| PyRuntimeError::new_err(format!("Cannot parse filter, {e}")))?,
+                None => None,
+            };
+            let sort_criteria = match sort {
+                Some(clauses) => Some(
+                    clauses
+                        .iter()
+                        .map(|clause| milli::AscDesc::from_str(clause))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| PyRuntimeError::new_err(format!("Cannot parse sort clause, {e}")))?,
+                ),
+                None => None,
+            };
+            let universe = filtered_universe(&ctx, &filter).map_err(|e| PyRuntimeError::new_err(format!("{}", e)))?;
             let docs = execute_search(
                 &mut ctx,
                 (!query.trim().is_empty()).then(|| query.trim()),
                 TermsMatchingStrategy::Last,
-                milli::score_details::ScoringStrategy::Skip,
+                if with_score {
+                    milli::score_details::ScoringStrategy::Detailed
+                } else {
+                    milli::score_details::ScoringStrategy::Skip
+                },
                 false,
                 universe,
-                &None,
+                &sort_criteria,
                 GeoSortStrategy::default(),
-                0,
-                20,
+                offset,
+                limit,
                 None,
                 &mut DefaultSearchLogger,
                 &mut DefaultSearchLogger,
@@ -123,7 +227,8 @@ impl MilliEmbedded {
                 .documents(&txn, docs.documents_ids.iter().copied())
                 .unwrap()
                 .into_iter()
-                .map(|(_id, obkv)| {
+                .enumerate()
+                .map(|(rank, (_id, obkv))| {
                     let mut object = serde_json::Map::default();
                     for (fid, fid_name) in self.index.fields_ids_map(&txn).unwrap().iter() {
                         if !return_fields.contains(fid_name) {
@@ -133,10 +238,24 @@ impl MilliEmbedded {
                         let value: serde_json::Value = serde_json::from_slice(value).unwrap();
                         object.insert(fid_name.to_owned(), value);
                     }
+                    if with_score {
+                        if let Some(scores) = docs.document_scores.get(rank) {
+                            let score = milli::score_details::ScoreDetails::global_score(scores.iter());
+                            if let Some(score) = serde_json::Number::from_f64(score) {
+                                object.insert("_rankingScore".to_owned(), serde_json::Value::Number(score));
+                            }
+                        }
+                    }
                     object
                 })
                 .collect::<serde_json::Value>();
-            Ok(format!("{documents}"))
+            let result = serde_json::json!({
+                "hits": documents,
+                "estimatedTotalHits": docs.candidates.len(),
+                "offset": offset,
+                "limit": limit,
+            });
+            Ok(format!("{result}"))
         })
     }
 }